@@ -1,13 +1,58 @@
-pub fn get_num_harts(dtb_addr: usize) -> usize {
+/// Upper bound on the number of harts `hart_ids` can report. The device tree
+/// is read before the buddy allocator backing the kernel heap is seeded, so
+/// no `Vec` can be allocated here; the result is a fixed-size array plus a
+/// count instead.
+pub const MAX_HARTS: usize = 8;
+
+/// Reads the hart ids of every `/cpus/cpu@*` node out of the device tree,
+/// for SMP bring-up to iterate over.
+pub fn hart_ids(dtb_addr: usize) -> ([usize; MAX_HARTS], usize) {
+    let fdt = unsafe {
+        fdt::Fdt::from_ptr(dtb_addr as *const u8)
+            .expect("Failed to parse device tree from dtb_addr")
+    };
+
+    let mut ids = [0usize; MAX_HARTS];
+    let mut count = 0;
+
+    for cpu in fdt.cpus() {
+        assert!(count < MAX_HARTS, "more harts than MAX_HARTS");
+
+        ids[count] = cpu.ids().first();
+        count += 1;
+    }
+
+    (ids, count)
+}
+
+/// Upper bound on the number of `/memory` regions `memory_regions` can
+/// report; see [`MAX_HARTS`] for why this is a fixed-size array rather than
+/// a `Vec`.
+pub const MAX_MEMORY_REGIONS: usize = 8;
+
+/// Reads the `/memory` node's `reg` property as `(base, size)` pairs,
+/// honoring the root node's `#address-cells`/`#size-cells`, so the buddy
+/// allocator can be seeded from however much RAM was actually handed to
+/// the machine instead of a hardcoded range.
+pub fn memory_regions(dtb_addr: usize) -> ([(usize, usize); MAX_MEMORY_REGIONS], usize) {
     let fdt = unsafe {
         fdt::Fdt::from_ptr(dtb_addr as *const u8)
             .expect("Failed to parse device tree from dtb_addr")
     };
-    let dtb_cpus = fdt.cpus();
-    let mut num_harts = 0;
 
-    for _cpu in dtb_cpus {
-        num_harts += 1;
+    let mut regions = [(0usize, 0usize); MAX_MEMORY_REGIONS];
+    let mut count = 0;
+
+    for region in fdt.memory().regions() {
+        assert!(
+            count < MAX_MEMORY_REGIONS,
+            "more memory regions than MAX_MEMORY_REGIONS"
+        );
+
+        let size = region.size.expect("memory region with no size");
+        regions[count] = (region.starting_address as usize, size);
+        count += 1;
     }
-    num_harts
+
+    (regions, count)
 }
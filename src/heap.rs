@@ -0,0 +1,237 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    marker::PhantomData,
+    ptr::NonNull,
+};
+
+use eonix_mm::paging::{PageAccess, PageAlloc, PFN};
+use spin::Mutex;
+
+/// Slab size classes, powers of two from 16 bytes up to half a page. A
+/// page-sized class would round `SlabPage`'s header up to the full page,
+/// leaving zero blocks to carve out of it; page-sized (and larger)
+/// allocations fall back to `alloc_pages` instead.
+const MIN_CLASS_SHIFT: usize = 4;
+const MAX_CLASS_SHIFT: usize = 11;
+const NUM_CLASSES: usize = MAX_CLASS_SHIFT - MIN_CLASS_SHIFT + 1;
+const PAGE_SHIFT: usize = 12;
+const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+
+const CLASS_SIZES: [usize; NUM_CLASSES] = {
+    let mut sizes = [0usize; NUM_CLASSES];
+    let mut i = 0;
+    while i < NUM_CLASSES {
+        sizes[i] = 1 << (MIN_CLASS_SHIFT + i);
+        i += 1;
+    }
+    sizes
+};
+
+const fn round_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+fn class_for_size(size: usize) -> Option<usize> {
+    CLASS_SIZES.iter().position(|&class_size| size <= class_size)
+}
+
+/// A free block within a slab page; the block's own memory stores the link.
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+/// Header living at the start of every slab page, doubling as the node for
+/// the class's "has a free block" list.
+struct SlabPage {
+    next: Option<NonNull<SlabPage>>,
+    free_list: Option<NonNull<FreeBlock>>,
+    allocated: usize,
+    pfn: PFN,
+}
+
+/// Header at the start of an allocation too big for any slab class,
+/// recording what to hand back to the page allocator on `dealloc`.
+struct BigHeader {
+    pfn: PFN,
+}
+
+struct SlabState {
+    classes: [Option<NonNull<SlabPage>>; NUM_CLASSES],
+}
+
+// Only ever touched with `state`'s mutex held.
+unsafe impl Send for SlabState {}
+
+/// A `GlobalAlloc` that carves sub-page allocations out of pages obtained
+/// from a [`PageAlloc`], maintaining one free list per size class, and
+/// falls back to whole-page (or multi-page) allocations from the same
+/// source for anything larger than the biggest class (half a page). Returns a page to `Alloc` once
+/// every block carved from it has been freed.
+pub struct SlabAllocator<Alloc, Access> {
+    alloc: Alloc,
+    state: Mutex<SlabState>,
+    _access: PhantomData<Access>,
+}
+
+impl<Alloc, Access> SlabAllocator<Alloc, Access> {
+    pub const fn new(alloc: Alloc) -> Self {
+        Self {
+            alloc,
+            state: Mutex::new(SlabState {
+                classes: [None; NUM_CLASSES],
+            }),
+            _access: PhantomData,
+        }
+    }
+}
+
+unsafe impl<Alloc, Access> GlobalAlloc for SlabAllocator<Alloc, Access>
+where
+    Alloc: PageAlloc,
+    Access: PageAccess,
+    Alloc::RawPage: Into<PFN> + From<PFN>,
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let size = layout.size().max(layout.align());
+
+        let Some(class) = class_for_size(size) else {
+            return unsafe { self.alloc_pages(layout) };
+        };
+
+        let mut state = self.state.lock();
+
+        if state.classes[class].is_none() {
+            let Some(page) = (unsafe { self.grow_class(&mut state, class) }) else {
+                return core::ptr::null_mut();
+            };
+            let _ = page;
+        }
+
+        let mut page_ptr = state.classes[class].expect("just grew this class");
+        let page = unsafe { page_ptr.as_mut() };
+
+        let mut block = page.free_list.expect("page in class list has a free block");
+        page.free_list = unsafe { block.as_ref().next };
+        page.allocated += 1;
+
+        if page.free_list.is_none() {
+            state.classes[class] = page.next;
+            page.next = None;
+        }
+
+        unsafe { block.as_mut() as *mut FreeBlock as *mut u8 }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let size = layout.size().max(layout.align());
+
+        let Some(class) = class_for_size(size) else {
+            return unsafe { self.dealloc_pages(ptr, layout) };
+        };
+
+        let page_addr = (ptr as usize) & !(PAGE_SIZE - 1);
+        let mut page_ptr = NonNull::new(page_addr as *mut SlabPage).expect("page_addr is non-null");
+        let page = unsafe { page_ptr.as_mut() };
+
+        let mut state = self.state.lock();
+
+        if page.free_list.is_none() {
+            // The page was fully allocated and so isn't on the class list;
+            // put it back now that it has a free block again.
+            page.next = state.classes[class];
+            state.classes[class] = Some(page_ptr);
+        }
+
+        let mut block = NonNull::new(ptr as *mut FreeBlock).expect("ptr is non-null");
+        unsafe { block.as_mut().next = page.free_list };
+        page.free_list = Some(block);
+        page.allocated -= 1;
+
+        if page.allocated == 0 {
+            Self::unlink_page(&mut state.classes[class], page_ptr);
+            unsafe { self.alloc.dealloc(Alloc::RawPage::from(page.pfn)) };
+        }
+    }
+}
+
+impl<Alloc, Access> SlabAllocator<Alloc, Access>
+where
+    Alloc: PageAlloc,
+    Access: PageAccess,
+    Alloc::RawPage: Into<PFN> + From<PFN>,
+{
+    /// Allocates a fresh page for `class`, carves it into free blocks, and
+    /// pushes it onto the class's page list.
+    unsafe fn grow_class(&self, state: &mut SlabState, class: usize) -> Option<NonNull<SlabPage>> {
+        let raw_page = self.alloc.alloc_order(0)?;
+        let pfn: PFN = raw_page.into();
+        let page_ptr = unsafe { Access::get_ptr_for_pfn(pfn) }.cast::<SlabPage>();
+
+        let class_size = CLASS_SIZES[class];
+        let header_size = round_up(core::mem::size_of::<SlabPage>(), class_size);
+        let block_count = (PAGE_SIZE - header_size) / class_size;
+        let base = page_ptr.as_ptr() as usize + header_size;
+
+        unsafe {
+            page_ptr.as_ptr().write(SlabPage {
+                next: state.classes[class],
+                free_list: None,
+                allocated: 0,
+                pfn,
+            });
+
+            let page = &mut *page_ptr.as_ptr();
+            for i in 0..block_count {
+                let block_ptr = (base + i * class_size) as *mut FreeBlock;
+                block_ptr.write(FreeBlock {
+                    next: page.free_list,
+                });
+                page.free_list = NonNull::new(block_ptr);
+            }
+        }
+
+        state.classes[class] = Some(page_ptr);
+        Some(page_ptr)
+    }
+
+    unsafe fn alloc_pages(&self, layout: Layout) -> *mut u8 {
+        let header_size = round_up(core::mem::size_of::<BigHeader>(), layout.align().max(1));
+        let total = header_size + layout.size();
+        let pages = total.div_ceil(PAGE_SIZE).max(1);
+        let order = pages.next_power_of_two().trailing_zeros();
+
+        let Some(raw_page) = self.alloc.alloc_order(order) else {
+            return core::ptr::null_mut();
+        };
+
+        let pfn: PFN = raw_page.into();
+        let base = unsafe { Access::get_ptr_for_pfn(pfn) }.as_ptr() as usize;
+
+        unsafe { (base as *mut BigHeader).write(BigHeader { pfn }) };
+
+        (base + header_size) as *mut u8
+    }
+
+    unsafe fn dealloc_pages(&self, ptr: *mut u8, layout: Layout) {
+        let header_size = round_up(core::mem::size_of::<BigHeader>(), layout.align().max(1));
+        let base = ptr as usize - header_size;
+        let header = unsafe { &*(base as *const BigHeader) };
+
+        unsafe { self.alloc.dealloc(Alloc::RawPage::from(header.pfn)) };
+    }
+
+    fn unlink_page(list: &mut Option<NonNull<SlabPage>>, target: NonNull<SlabPage>) {
+        let mut cursor = list;
+
+        loop {
+            match *cursor {
+                Some(mut node) if node == target => {
+                    *cursor = unsafe { node.as_mut().next };
+                    return;
+                }
+                Some(mut node) => cursor = unsafe { &mut node.as_mut().next },
+                None => unreachable!("page being freed was not in its class list"),
+            }
+        }
+    }
+}
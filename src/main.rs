@@ -1,17 +1,24 @@
 #![no_std]
 #![no_main]
 #![feature(naked_functions)]
+#![feature(alloc_error_handler)]
+
+extern crate alloc;
 
 mod rv64_mm;
 mod fdt;
+mod heap;
+mod mem_set;
 
 use fdt::*;
+use heap::SlabAllocator;
+use mem_set::{MapArea, MapKind, MemorySet};
 
 use buddy_allocator::{BuddyAllocator, BuddyRawPage};
 use core::{
     arch::{global_asm, naked_asm},
     ptr::NonNull,
-    sync::atomic::AtomicUsize,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 use eonix_mm::{
     address::{Addr as _, AddrOps, PAddr, VAddr, VRange},
@@ -19,10 +26,33 @@ use eonix_mm::{
     paging::{Page, PageAccess, PageAlloc, PageBlock, RawPage as RawPageTrait, PFN},
 };
 use intrusive_list::{container_of, Link};
-use riscv::{asm::sfence_vma_all, register::satp};
+use riscv::{
+    asm::{sfence_vma, sfence_vma_all},
+    register::{
+        satp,
+        scause::{self, Exception, Trap},
+        sepc, stval, stvec,
+    },
+};
 use rv64_mm::*;
 use spin::Mutex;
 
+// `riscv.riscv32` only wires up `RV32` as a `PagingMode` so far. The boot
+// path below it — `BootPageTable`'s 64-bit Sv39-shaped entries, `_start`'s
+// naked asm, and the higher-half virtual layout in `KIMAGE_VIRT_BASE` /
+// `MMIO_VIRT_BASE` / `PHYS_MAP_VIRT` — assumes a 64-bit negative/canonical
+// address space that Sv32's 32-bit addresses can't represent, so this
+// binary cannot actually reach `riscv64_start` under it. Fail the build
+// instead of shipping a kernel that silently can't boot; a real Sv32
+// target needs its own entry path and virtual memory layout built around
+// a 32-bit address space, not just this cfg.
+#[cfg(feature = "riscv.riscv32")]
+compile_error!(
+    "riscv.riscv32 is not wired up end to end yet: BootPageTable, _start, \
+     and the higher-half virtual memory layout all assume a 64-bit \
+     address space. See the comment above this compile_error! in main.rs."
+);
+
 //global_asm!(include_str!("entry.S"));
 
 #[link_section = ".bootstack"]
@@ -39,8 +69,43 @@ static mut BOOT_PAGE_TABLE: BootPageTable = {
     BootPageTable(arr)
 };
 
+/// Upper bound on how much RAM `PAGES` can back with per-page metadata.
+/// There's no heap yet at the point `PAGES` needs to exist (it's what the
+/// buddy allocator that backs the heap is built on), so this can't be
+/// sized from the device tree; 1 GiB covers any realistic `-m` size for
+/// this target, and `init_pages` asserts the discovered range actually
+/// fits instead of silently wrapping.
+const MAX_MANAGED_PAGES: usize = 0x40000;
+
 #[link_section = ".bootdata"]
-static mut PAGES: [RawPage; 1024] = [const { RawPage::new() }; 1024];
+static mut PAGES: [RawPage; MAX_MANAGED_PAGES] =
+    [const { RawPage::new() }; MAX_MANAGED_PAGES];
+
+/// PFN of `PAGES[0]`, i.e. the first page of the discovered free region.
+/// Set once by `init_pages` before the buddy allocator is used.
+static mut PAGE_BASE_PFN: usize = 0;
+/// Number of pages `PAGES` actually backs, i.e. the discovered free
+/// region's length. Set once by `init_pages`.
+static mut PAGE_COUNT: usize = 0;
+
+/// Points `PAGES`' metadata at the free region `[free_start, free_end)`
+/// discovered from the device tree, replacing the PFN range `RawPageHandle`
+/// used to hardcode. Must run before the buddy allocator manages any pages
+/// from that range.
+fn init_pages(free_start: PAddr, free_end: PAddr) {
+    let base_pfn = free_start.addr() >> PAGE_SIZE_BITS;
+    let count = (free_end.addr() - free_start.addr()) >> PAGE_SIZE_BITS;
+
+    assert!(
+        count <= MAX_MANAGED_PAGES,
+        "discovered RAM needs more page metadata than MAX_MANAGED_PAGES"
+    );
+
+    unsafe {
+        PAGE_BASE_PFN = base_pfn;
+        PAGE_COUNT = count;
+    }
+}
 
 const fn page(index: usize) -> &'static mut RawPage {
     let page = unsafe { PAGES.as_mut_ptr().add(index) };
@@ -73,15 +138,17 @@ struct RawPageHandle(usize);
 
 impl From<PFN> for RawPageHandle {
     fn from(pfn: PFN) -> Self {
-        assert!(usize::from(pfn) - 0x80400 < 1024, "PFN out of range");
+        let base = unsafe { PAGE_BASE_PFN };
+        let index = usize::from(pfn) - base;
+        assert!(index < unsafe { PAGE_COUNT }, "PFN out of range");
 
-        Self(usize::from(pfn) - 0x80400)
+        Self(index)
     }
 }
 
 impl From<RawPageHandle> for PFN {
     fn from(raw_page: RawPageHandle) -> Self {
-        PFN::from(raw_page.0 + 0x80400)
+        PFN::from(raw_page.0 + unsafe { PAGE_BASE_PFN })
     }
 }
 
@@ -95,7 +162,7 @@ impl RawPageTrait for RawPageHandle {
     }
 
     fn is_present(&self) -> bool {
-        self.0 < 1024
+        self.0 < unsafe { PAGE_COUNT }
     }
 }
 
@@ -103,7 +170,7 @@ impl BuddyRawPage for RawPageHandle {
     unsafe fn from_link(link: &mut Link) -> Self {
         let page = container_of!(link, RawPage, link);
         let page_index = page.as_ptr().offset_from_unsigned(PAGES.as_ptr()) as usize;
-        assert!(page_index < 1024, "Page index out of range");
+        assert!(page_index < unsafe { PAGE_COUNT }, "Page index out of range");
 
         Self(page_index)
     }
@@ -201,7 +268,26 @@ impl PageAlloc for BuddyPageAlloc {
     }
 }
 
-type PageTable<'a> = eonix_mm::page_table::PageTable<'a, PagingModeSv39, BuddyPageAlloc, DirectPageAccess>;
+type PageTable<'a> = eonix_mm::page_table::PageTable<'a, PagingModeImpl, BuddyPageAlloc, DirectPageAccess>;
+
+#[global_allocator]
+static ALLOCATOR: SlabAllocator<BuddyPageAlloc, DirectPageAccess> =
+    SlabAllocator::new(BuddyPageAlloc);
+
+/// The kernel's own address space, shared with `trap_handler` so demand
+/// paging faults taken on the kernel map can be resolved there.
+static KERNEL_MEMORY_SET: Mutex<
+    Option<MemorySet<PagingModeImpl, BuddyPageAlloc, DirectPageAccess>>,
+> = Mutex::new(None);
+
+#[alloc_error_handler]
+fn alloc_error(layout: core::alloc::Layout) -> ! {
+    print("out of memory allocating ");
+    print_number(layout.size());
+    print(" bytes\n");
+
+    loop {}
+}
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
@@ -259,6 +345,68 @@ extern "C" {
     fn _ekernel();
 }
 
+#[cfg(all(not(feature = "riscv.riscv32"), feature = "riscv.pagetable.sv57"))]
+const SATP_MODE: usize = 10 << 60;
+#[cfg(all(
+    not(feature = "riscv.riscv32"),
+    feature = "riscv.pagetable.sv48",
+    not(feature = "riscv.pagetable.sv57")
+))]
+const SATP_MODE: usize = 9 << 60;
+#[cfg(all(
+    not(feature = "riscv.riscv32"),
+    not(feature = "riscv.pagetable.sv48"),
+    not(feature = "riscv.pagetable.sv57")
+))]
+const SATP_MODE: usize = 8 << 60;
+// Sv32's satp packs the mode into a single bit (31) rather than a 4-bit
+// field at the top of a 64-bit register.
+#[cfg(feature = "riscv.riscv32")]
+const SATP_MODE: usize = 1 << 31;
+
+#[cfg(all(not(feature = "riscv.riscv32"), feature = "riscv.pagetable.sv57"))]
+const SATP_MODE_ENUM: satp::Mode = satp::Mode::Sv57;
+#[cfg(all(
+    not(feature = "riscv.riscv32"),
+    feature = "riscv.pagetable.sv48",
+    not(feature = "riscv.pagetable.sv57")
+))]
+const SATP_MODE_ENUM: satp::Mode = satp::Mode::Sv48;
+#[cfg(all(
+    not(feature = "riscv.riscv32"),
+    not(feature = "riscv.pagetable.sv48"),
+    not(feature = "riscv.pagetable.sv57")
+))]
+const SATP_MODE_ENUM: satp::Mode = satp::Mode::Sv39;
+#[cfg(feature = "riscv.riscv32")]
+const SATP_MODE_ENUM: satp::Mode = satp::Mode::Sv32;
+
+/// Bits of virtual address covered by each table level's index, i.e. how
+/// many entries (as a power of two) a single table level has: 9 for
+/// Sv39/Sv48/Sv57, 10 for Sv32.
+#[cfg(feature = "riscv.riscv32")]
+const LEVEL_WIDTH_BITS: u32 = 10;
+#[cfg(not(feature = "riscv.riscv32"))]
+const LEVEL_WIDTH_BITS: u32 = 9;
+
+/// Index (from the root) of the leaf level, i.e. the one mapping ordinary
+/// 4KB pages.
+const LEAF_LEVEL: usize = PagingModeImpl::LEVELS.len() - 1;
+/// Index of the level one step coarser than the leaf: 2MB pages on
+/// Sv39/48/57, or the same as [`TOP_LEVEL`] on the 2-level Sv32.
+const MID_LEVEL: usize = PagingModeImpl::LEVELS.len().saturating_sub(2);
+/// Index of the level two steps coarser than the leaf: 1GB pages, where
+/// that many levels exist (Sv39/48/57). Sv32 only has two levels, so this
+/// saturates down to [`MID_LEVEL`] and the two huge-page tiers collapse
+/// into Sv32's single 4MB megapage tier.
+const TOP_LEVEL: usize = PagingModeImpl::LEVELS.len().saturating_sub(3);
+
+/// Number of leaf-level (4KB) pages spanned by one entry at `MID_LEVEL`
+/// and `TOP_LEVEL` respectively, counted leaf-relative so it stays correct
+/// regardless of how many finer-than-`TOP_LEVEL` levels a mode has.
+const MID_STRIDE: usize = 1usize << ((LEAF_LEVEL - MID_LEVEL) as u32 * LEVEL_WIDTH_BITS);
+const TOP_STRIDE: usize = 1usize << ((LEAF_LEVEL - TOP_LEVEL) as u32 * LEVEL_WIDTH_BITS);
+
 /// bootstrap in rust
 #[naked]
 #[no_mangle]
@@ -269,7 +417,7 @@ unsafe extern "C" fn _start(hart_id: usize, dtb_addr: usize) -> ! {
         la   sp, {boot_stack}
         la   t0, {page_table}
         srli t0, t0, 12
-        li   t1, 8 << 60
+        li   t1, {satp_mode}
         or   t0, t0, t1
         csrw satp, t0
         sfence.vma
@@ -281,24 +429,25 @@ unsafe extern "C" fn _start(hart_id: usize, dtb_addr: usize) -> ! {
         ",
         boot_stack = sym BOOT_STACK,
         page_table = sym BOOT_PAGE_TABLE,
+        satp_mode = const SATP_MODE,
         virt_ram_offset = const KIMAGE_OFFSET,
     )
 }
 
-fn map_physical_memory(page_table: &PageTable, attr: PageAttribute) {
+fn map_physical_memory(page_table: &PageTable, attr: PageAttribute, mem_size: usize) {
     let ekernel = _ekernel as usize - 0xffff_ffff_0000_0000;
 
     let start = PAddr::from(ekernel).ceil_to(PageSize::_4KbPage as usize);
     let end = PAddr::from(ekernel).ceil_to(PageSize::_2MbPage as usize);
     let size_4kb = end - start;
-    let range = VRange::from(VAddr::from(PHYS_MAP_VIRT + start.addr())).grow(size_4kb);
+    let range = VRange::from(VAddr::from(canonicalize_addr(PHYS_MAP_VIRT + start.addr()))).grow(size_4kb);
     let pfn_start = start.addr() >> PAGE_SIZE_BITS;
     print_number(range.start().addr() - PHYS_MAP_VIRT);
     print("\n");
     print_number(start.addr());
     print("\n");
     for (idx, pte) in page_table
-        .iter_kernel_levels(range, &PagingModeSv39::LEVELS[..=2])
+        .iter_kernel_levels(range, &PagingModeImpl::LEVELS[..=LEAF_LEVEL])
         .enumerate()
     {
         pte.set(PFN::from(idx + pfn_start), PageAttribute64::from_page_attr(attr));
@@ -307,49 +456,216 @@ fn map_physical_memory(page_table: &PageTable, attr: PageAttribute) {
     let start = end;
     let end = start.ceil_to(PageSize::_1GbPage as usize);
     let size_2mb = end - start;
-    let range = VRange::from(VAddr::from(PHYS_MAP_VIRT + start.addr())).grow(size_2mb);
+    let range = VRange::from(VAddr::from(canonicalize_addr(PHYS_MAP_VIRT + start.addr()))).grow(size_2mb);
     let pfn_start = start.addr() >> PAGE_SIZE_BITS;
     print_number(range.start().addr() - PHYS_MAP_VIRT);
     print("\n");
     print_number(start.addr());
     print("\n");
     for (idx, pte) in page_table
-        .iter_kernel_levels(range, &PagingModeSv39::LEVELS[..=1])
+        .iter_kernel_levels(range, &PagingModeImpl::LEVELS[..=MID_LEVEL])
         .enumerate()
     {
-        pte.set(PFN::from(idx * 0x200 + pfn_start), PageAttribute64::from_page_attr(attr));
+        pte.set(PFN::from(idx * MID_STRIDE + pfn_start), PageAttribute64::from_page_attr(attr));
     }
 
     let start = end;
-    let size_1gb = MEMORY_SIZE;
-    let range = VRange::from(VAddr::from(PHYS_MAP_VIRT + start.addr())).grow(size_1gb);
+    let size_1gb = mem_size;
+    let range = VRange::from(VAddr::from(canonicalize_addr(PHYS_MAP_VIRT + start.addr()))).grow(size_1gb);
     let pfn_start = start.addr() >> PAGE_SIZE_BITS;
     print_number(range.start().addr() - PHYS_MAP_VIRT);
     print("\n");
     print_number(start.addr());
     print("\n");
     for (idx, pte) in page_table
-        .iter_kernel_levels(range, &PagingModeSv39::LEVELS[..=0])
+        .iter_kernel_levels(range, &PagingModeImpl::LEVELS[..=TOP_LEVEL])
         .enumerate()
     {
-        pte.set(PFN::from(idx * 0x40000 + pfn_start), PageAttribute64::from_page_attr(attr));
+        pte.set(PFN::from(idx * TOP_STRIDE + pfn_start), PageAttribute64::from_page_attr(attr));
     }
 }
 
+/// Per-hart boot stack for application harts, indexed by hart id. Mirrors
+/// `BOOT_STACK`, just one slot per hart instead of a single shared one.
+const AP_BOOT_STACK_SIZE: usize = 4096 * 4;
+
+#[link_section = ".bootdata"]
+static mut AP_BOOT_STACKS: [[u8; AP_BOOT_STACK_SIZE]; MAX_HARTS] =
+    [[0; AP_BOOT_STACK_SIZE]; MAX_HARTS];
+
+/// Harts that have reached `riscv64_start_ap`, counting the boot hart.
+/// The boot hart spins on this after `hart_start`ing the others so it
+/// doesn't move on until every hart has reported in.
+static HARTS_ONLINE: AtomicUsize = AtomicUsize::new(1);
+
 global_asm!(
     r#"
     .section .text.ap_boot
     .globl ap_boot_entry
 
     ap_boot_entry:
-        csrr a0, mhartid
-    "#
+        // SBI HSM hart_start ABI: a0 = hart id, a1 = opaque, here the
+        // already-built kernel satp value.
+        la   t0, {ap_boot_stacks}
+        li   t1, {ap_boot_stack_size}
+        mul  t2, a0, t1
+        add  sp, t0, t2
+        add  sp, sp, t1
+        csrw satp, a1
+        sfence.vma
+        li   t3, {virt_ram_offset}
+        or   sp, sp, t3
+        la   t4, riscv64_start_ap
+        or   t4, t4, t3
+        jalr t4                      // call riscv64_start_ap
+    "#,
+    ap_boot_stacks = sym AP_BOOT_STACKS,
+    ap_boot_stack_size = const AP_BOOT_STACK_SIZE,
+    virt_ram_offset = const KIMAGE_OFFSET,
 );
 
 extern "C" {
     fn ap_boot_entry();
 }
 
+global_asm!(
+    r#"
+    .section .text
+    .align 2
+    .globl trap_entry
+
+    trap_entry:
+        addi sp, sp, -256
+        sd   x1,  8(sp)
+        sd   x3,  24(sp)
+        sd   x4,  32(sp)
+        sd   x5,  40(sp)
+        sd   x6,  48(sp)
+        sd   x7,  56(sp)
+        sd   x8,  64(sp)
+        sd   x9,  72(sp)
+        sd   x10, 80(sp)
+        sd   x11, 88(sp)
+        sd   x12, 96(sp)
+        sd   x13, 104(sp)
+        sd   x14, 112(sp)
+        sd   x15, 120(sp)
+        sd   x16, 128(sp)
+        sd   x17, 136(sp)
+        sd   x18, 144(sp)
+        sd   x19, 152(sp)
+        sd   x20, 160(sp)
+        sd   x21, 168(sp)
+        sd   x22, 176(sp)
+        sd   x23, 184(sp)
+        sd   x24, 192(sp)
+        sd   x25, 200(sp)
+        sd   x26, 208(sp)
+        sd   x27, 216(sp)
+        sd   x28, 224(sp)
+        sd   x29, 232(sp)
+        sd   x30, 240(sp)
+        sd   x31, 248(sp)
+        call trap_handler
+        ld   x1,  8(sp)
+        ld   x3,  24(sp)
+        ld   x4,  32(sp)
+        ld   x5,  40(sp)
+        ld   x6,  48(sp)
+        ld   x7,  56(sp)
+        ld   x8,  64(sp)
+        ld   x9,  72(sp)
+        ld   x10, 80(sp)
+        ld   x11, 88(sp)
+        ld   x12, 96(sp)
+        ld   x13, 104(sp)
+        ld   x14, 112(sp)
+        ld   x15, 120(sp)
+        ld   x16, 128(sp)
+        ld   x17, 136(sp)
+        ld   x18, 144(sp)
+        ld   x19, 152(sp)
+        ld   x20, 160(sp)
+        ld   x21, 168(sp)
+        ld   x22, 176(sp)
+        ld   x23, 184(sp)
+        ld   x24, 192(sp)
+        ld   x25, 200(sp)
+        ld   x26, 208(sp)
+        ld   x27, 216(sp)
+        ld   x28, 224(sp)
+        ld   x29, 232(sp)
+        ld   x30, 240(sp)
+        ld   x31, 248(sp)
+        addi sp, sp, 256
+        sret
+    "#,
+);
+
+extern "C" {
+    fn trap_entry();
+}
+
+/// Decodes `scause`/`stval` and tries to resolve the fault through
+/// `KERNEL_MEMORY_SET`'s demand paging (the `MAPPED` and `COPY_ON_WRITE`
+/// software PTE bits); anything else falls through to `panic!` with the
+/// faulting `stval`/`sepc` printed.
+#[no_mangle]
+extern "C" fn trap_handler() {
+    let cause = scause::read().cause();
+    let fault_addr = stval::read();
+
+    let is_page_fault = matches!(
+        cause,
+        Trap::Exception(
+            Exception::StorePageFault
+                | Exception::LoadPageFault
+                | Exception::InstructionPageFault
+        )
+    );
+    let is_write = matches!(cause, Trap::Exception(Exception::StorePageFault));
+
+    if is_page_fault {
+        let handled = KERNEL_MEMORY_SET
+            .lock()
+            .as_mut()
+            .expect("page fault taken before KERNEL_MEMORY_SET was initialized")
+            .handle_fault(VAddr::from(fault_addr), is_write);
+
+        if handled {
+            unsafe { sfence_vma(fault_addr, 0) };
+            return;
+        }
+    }
+
+    print("unhandled trap: stval = ");
+    print_number(fault_addr);
+    print(", sepc = ");
+    print_number(sepc::read());
+    print("\n");
+
+    panic!("unhandled trap");
+}
+
+/// Entry point for every hart other than the boot hart, reached through
+/// `ap_boot_entry` once it has installed the kernel `satp`.
+#[no_mangle]
+pub unsafe extern "C" fn riscv64_start_ap(hart_id: usize) -> ! {
+    print("AP hart online: ");
+    print_number(hart_id);
+    print("\n");
+
+    unsafe {
+        stvec::write(trap_entry as usize, stvec::TrapMode::Direct);
+    }
+
+    HARTS_ONLINE.fetch_add(1, Ordering::SeqCst);
+
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn riscv64_start(hart_id: usize, dtb_addr: usize) -> ! {
     print("\n");
@@ -363,13 +679,24 @@ pub unsafe extern "C" fn riscv64_start(hart_id: usize, dtb_addr: usize) -> ! {
     print_number(hart_id);
     print("\n");
 
-    let num_harts = get_num_harts(dtb_addr);
+    let (hart_ids, num_harts) = hart_ids(dtb_addr);
     print_number(num_harts);
     print("\n");
 
-    BUDDY
-        .lock()
-        .create_pages(PAddr::from(0x80400000), PAddr::from(0x80700000));
+    let (memory_regions, memory_region_count) = memory_regions(dtb_addr);
+    let (mem_start, mem_size) = memory_regions[..memory_region_count]
+        .iter()
+        .copied()
+        .max_by_key(|&(_, size)| size)
+        .expect("no usable memory regions in device tree");
+
+    let ekernel_phys = _ekernel as usize - 0xffff_ffff_0000_0000;
+    let page_size = PageSize::_4KbPage as usize;
+    let free_start = PAddr::from(ekernel_phys.max(mem_start)).ceil_to(page_size);
+    let free_end = PAddr::from((mem_start + mem_size) & !(page_size - 1));
+
+    init_pages(free_start, free_end);
+    BUDDY.lock().create_pages(free_start, free_end);
 
     let root_table_page = Page::alloc_in(BuddyPageAlloc);
 
@@ -384,41 +711,80 @@ pub unsafe extern "C" fn riscv64_start(hart_id: usize, dtb_addr: usize) -> ! {
     // Map 0x00000000-0x7fffffff 2GB MMIO,
     // to 0xffff ffff 0000 0000 to 0xffff ffff 7ffff ffff, use 1GB page
     for (idx, pte) in page_table
-        .iter_kernel_levels(VRange::from(VAddr::from(MMIO_VIRT_BASE)).grow(0x2000_0000), &PagingModeSv39::LEVELS[..=0])
+        .iter_kernel_levels(
+            VRange::from(VAddr::from(canonicalize_addr(MMIO_VIRT_BASE))).grow(0x2000_0000),
+            &PagingModeImpl::LEVELS[..=TOP_LEVEL],
+        )
         .enumerate()
     {
-        pte.set(PFN::from(idx * 0x40000), PageAttribute64::from_page_attr(attr));
+        pte.set(PFN::from(idx * TOP_STRIDE), PageAttribute64::from_page_attr(attr));
     }
 
-    map_physical_memory(&page_table, attr);
+    map_physical_memory(&page_table, attr, mem_size);
 
     /*// Map 0x0000_0000_0000_0000-0x0000_001F_FFFF_FFFF 128GB
     // to 0xffff_ffd6_0000_0000 to 0xffff_fff5_ffff_ffff, use 1 GB page
     for (idx, pte) in page_table
-        .iter_kernel_levels(VRange::from(VAddr::from(PHYS_MAP_VIRT)).grow(0x20_0000_0000), &PagingModeSv39::LEVELS[..=0])
+        .iter_kernel_levels(VRange::from(VAddr::from(PHYS_MAP_VIRT)).grow(0x20_0000_0000), &PagingModeImpl::LEVELS[..=0])
         .enumerate()
     {
         pte.set(PFN::from(idx * 0x40000), PageAttribute64::from_page_attr(attr));
     }*/
 
-    // Map 2 MB kernel image
-    for (idx, pte) in page_table
-        .iter_kernel(VRange::from(VAddr::from(KIMAGE_VIRT_BASE)).grow(0x20_0000))
-        .enumerate()
-    {
-        pte.set(PFN::from(idx + 0x80200), PageAttribute64::from_page_attr(attr));
-    }
+    let page_table_pfn = PFN::from(page_table.addr());
 
-    unsafe {
-        satp::set(
-            satp::Mode::Sv39,
-            0,
-            usize::from(PFN::from(page_table.addr())),
+    // Hand the rest of the kernel map over to a MemorySet, instead of
+    // poking PTEs by hand like the loops above still do for the huge-page
+    // MMIO/physical map windows.
+    let mut kernel_memory_set =
+        MemorySet::<PagingModeImpl, BuddyPageAlloc, DirectPageAccess>::new_in(
+            root_table_page,
+            BuddyPageAlloc,
         );
+
+    kernel_memory_set.push(
+        MapArea::new(
+            VRange::from(VAddr::from(canonicalize_addr(KIMAGE_VIRT_BASE))).grow(0x20_0000),
+            attr,
+            MapKind::Offset(KIMAGE_VIRT_BASE - 0x80200000),
+        ),
+        None,
+    );
+
+    *KERNEL_MEMORY_SET.lock() = Some(kernel_memory_set);
+
+    unsafe {
+        satp::set(SATP_MODE_ENUM, 0, usize::from(page_table_pfn));
     }
     sfence_vma_all();
 
     print("paging enabled\n");
+
+    unsafe {
+        stvec::write(trap_entry as usize, stvec::TrapMode::Direct);
+    }
+
+    let satp_value = SATP_MODE | usize::from(page_table_pfn);
+    let ap_boot_entry_paddr = ap_boot_entry as usize - KIMAGE_OFFSET;
+
+    for &id in &hart_ids[..num_harts] {
+        if id == hart_id {
+            continue;
+        }
+
+        // ap_boot_entry indexes AP_BOOT_STACKS by raw hart id; the device
+        // tree doesn't guarantee hart ids are a dense 0..MAX_HARTS range.
+        assert!(id < MAX_HARTS, "hart id out of range for AP_BOOT_STACKS");
+
+        sbi::hsm::hart_start(id, ap_boot_entry_paddr, satp_value)
+            .expect("failed to start secondary hart");
+    }
+
+    while HARTS_ONLINE.load(Ordering::SeqCst) < num_harts {
+        core::hint::spin_loop();
+    }
+
+    print("all harts online\n");
     print("stack message:\n");
     print_number(BOOT_STACK.as_ptr() as usize - KIMAGE_OFFSET);
     print("\n");
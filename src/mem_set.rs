@@ -0,0 +1,286 @@
+use core::{marker::PhantomData, sync::atomic::Ordering};
+
+use eonix_mm::{
+    address::{Addr as _, AddrOps, VAddr, VRange},
+    page_table::{PageAttribute, PageTable, PagingMode, RawAttribute, PTE},
+    paging::{Page, PageAccess, PageAlloc, RawPage as RawPageTrait, PFN},
+};
+
+/// Maximum number of [`MapArea`]s a single [`MemorySet`] can track.
+///
+/// A fixed-capacity table is used in place of a growable collection; this is
+/// large enough for the handful of regions the boot-time kernel map needs.
+const MAX_AREAS: usize = 64;
+
+const PAGE_SHIFT: usize = 12;
+const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+
+/// How a [`MapArea`]'s pages are backed.
+#[derive(Clone, Copy)]
+pub enum MapKind {
+    /// `vaddr = paddr + offset`, e.g. the physical memory map, MMIO windows
+    /// and the kernel image. No frames are allocated or freed for these
+    /// areas; they just poke PTEs at a fixed translation.
+    Offset(usize),
+    /// Individually allocated physical frames, owned by the area.
+    Framed,
+}
+
+/// A single mapped virtual memory region: a range, its permissions, and how
+/// its pages are backed. Mirrors the `MapArea` of the rCore `memory_set`
+/// design.
+#[derive(Clone, Copy)]
+pub struct MapArea {
+    range: VRange,
+    attr: PageAttribute,
+    kind: MapKind,
+}
+
+impl MapArea {
+    pub fn new(range: VRange, attr: PageAttribute, kind: MapKind) -> Self {
+        Self { range, attr, kind }
+    }
+
+    pub fn range(&self) -> VRange {
+        self.range
+    }
+}
+
+/// Owns a [`PageTable`] and the set of [`MapArea`]s mapped into it, so
+/// callers describe virtual memory regions instead of poking PTEs by hand.
+/// Used today for the kernel's own address space, and intended to double as
+/// the basis for per-process address spaces later.
+pub struct MemorySet<Mode, Alloc, Access>
+where
+    Mode: PagingMode,
+    Alloc: PageAlloc + Clone,
+    Access: PageAccess,
+{
+    root: Page,
+    alloc: Alloc,
+    areas: [Option<MapArea>; MAX_AREAS],
+    _mode: PhantomData<Mode>,
+    _access: PhantomData<Access>,
+}
+
+impl<Mode, Alloc, Access> MemorySet<Mode, Alloc, Access>
+where
+    Mode: PagingMode,
+    Alloc: PageAlloc + Clone,
+    Access: PageAccess,
+    <Mode as PagingMode>::Entry: PTE,
+    <<Mode as PagingMode>::Entry as PTE>::Attr: RawAttribute,
+    <Alloc as PageAlloc>::RawPage: Into<PFN> + From<PFN> + RawPageTrait,
+{
+    /// Takes ownership of an already-allocated root table page, matching
+    /// `PageTable::new_in`'s pattern of building the table around a page
+    /// the caller allocated.
+    pub fn new_in(root: Page, alloc: Alloc) -> Self {
+        Self {
+            root,
+            alloc,
+            areas: [None; MAX_AREAS],
+            _mode: PhantomData,
+            _access: PhantomData,
+        }
+    }
+
+    fn page_table(&self) -> PageTable<'_, Mode, Alloc, Access> {
+        PageTable::new_in(&self.root, self.alloc.clone())
+    }
+
+    /// Maps `area` into the page table, allocating frames for [`MapKind::Framed`]
+    /// areas and, if `data` is given, copying it in (zero-filling the rest).
+    pub fn push(&mut self, area: MapArea, data: Option<&[u8]>) {
+        let page_table = self.page_table();
+
+        for (idx, pte) in page_table.iter_kernel(area.range).enumerate() {
+            let pfn = match area.kind {
+                MapKind::Offset(offset) => {
+                    let vaddr = area.range.start().addr() + idx * PAGE_SIZE;
+                    PFN::from((vaddr - offset) >> PAGE_SHIFT)
+                }
+                MapKind::Framed => {
+                    let frame = self
+                        .alloc
+                        .alloc_order(0)
+                        .expect("out of memory mapping a framed area");
+                    // Framed pages start out singly-owned; `handle_fault`'s
+                    // COW path decrements this and only frees the frame
+                    // once the last mapping drops it.
+                    frame.refcount().store(1, Ordering::Release);
+                    let pfn = frame.into();
+
+                    if let Some(bytes) = data {
+                        let offset = idx * PAGE_SIZE;
+                        let page_ptr = unsafe { Access::get_ptr_for_pfn(pfn) }.as_ptr() as *mut u8;
+                        let copy_len = bytes.len().saturating_sub(offset).min(PAGE_SIZE);
+
+                        unsafe {
+                            core::ptr::copy_nonoverlapping(
+                                bytes.as_ptr().add(offset.min(bytes.len())),
+                                page_ptr,
+                                copy_len,
+                            );
+                            core::ptr::write_bytes(
+                                page_ptr.add(copy_len),
+                                0,
+                                PAGE_SIZE - copy_len,
+                            );
+                        }
+                    }
+
+                    pfn
+                }
+            };
+
+            pte.set(
+                pfn,
+                <<Mode as PagingMode>::Entry as PTE>::Attr::from_page_attr(area.attr),
+            );
+        }
+
+        let slot = self
+            .areas
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .expect("MemorySet area table is full");
+        *slot = Some(area);
+    }
+
+    /// Unmaps the area previously `push`ed with this exact `range`, freeing
+    /// its frames if it was [`MapKind::Framed`].
+    pub fn remove(&mut self, range: VRange) {
+        let slot = self
+            .areas
+            .iter_mut()
+            .find(|slot| matches!(slot, Some(area) if area.range.start() == range.start() && area.range.end() == range.end()))
+            .expect("no such mapped area");
+        let area = slot.take().expect("area slot was empty");
+
+        let page_table = self.page_table();
+        for pte in page_table.iter_kernel(area.range) {
+            let (pfn, _attr) = pte.get();
+
+            if let MapKind::Framed = area.kind {
+                // Only the last mapping sharing this frame actually frees it;
+                // mirrors the `handle_fault` COW path below.
+                let frame = Alloc::RawPage::from(pfn);
+                if frame.refcount().fetch_sub(1, Ordering::AcqRel) == 1 {
+                    unsafe { self.alloc.dealloc(frame) };
+                }
+            }
+
+            pte.set(
+                PFN::from(0),
+                <<Mode as PagingMode>::Entry as PTE>::Attr::null(),
+            );
+        }
+    }
+
+    /// Resolves `vaddr` to its backing frame and permissions, if mapped.
+    pub fn translate(&self, vaddr: VAddr) -> Option<(PFN, PageAttribute)> {
+        let page_start = VAddr::from(vaddr.addr() & !(PAGE_SIZE - 1));
+        let range = VRange::from(page_start).grow(PAGE_SIZE);
+
+        let page_table = self.page_table();
+        let pte = page_table.iter_kernel(range).next()?;
+        let (pfn, attr) = pte.get();
+
+        Some((pfn, attr.as_page_attr()?))
+    }
+
+    /// Resolves a page fault at `vaddr`, if it falls in a [`MapArea`] this
+    /// set knows about and the fault is one demand paging can service:
+    ///
+    /// - A `MAPPED`-but-not-`PRESENT` page: allocate and zero a fresh frame
+    ///   and install it with the area's permissions.
+    /// - A write to a `COPY_ON_WRITE` page: allocate a fresh frame, copy the
+    ///   old page's contents over, drop the old frame's refcount, and
+    ///   install the new frame writable with the COW bit cleared.
+    ///
+    /// Returns `false` if `vaddr` isn't covered by any area or the fault
+    /// isn't one of the above, leaving it for the caller to treat as fatal.
+    pub fn handle_fault(&mut self, vaddr: VAddr, is_write: bool) -> bool {
+        let page_start = VAddr::from(vaddr.addr() & !(PAGE_SIZE - 1));
+        let range = VRange::from(page_start).grow(PAGE_SIZE);
+
+        let Some(area) = self
+            .areas
+            .iter()
+            .flatten()
+            .find(|area| area.range.start() <= range.start() && range.end() <= area.range.end())
+            .copied()
+        else {
+            return false;
+        };
+
+        let page_table = self.page_table();
+        let Some(pte) = page_table.iter_kernel(range).next() else {
+            return false;
+        };
+        let (pfn, attr) = pte.get();
+        let Some(page_attr) = attr.as_page_attr() else {
+            return false;
+        };
+
+        // `MapKind` has no lazy variant yet — `push` always either points
+        // straight at an offset translation or eagerly allocates and installs
+        // a frame — so no PTE this crate installs is ever MAPPED without
+        // also being PRESENT. This branch is unreachable today; it's kept
+        // (rather than removed) as the landing spot for a real demand-paging
+        // `MapKind` variant later.
+        if page_attr.contains(PageAttribute::MAPPED) && !page_attr.contains(PageAttribute::PRESENT)
+        {
+            let frame = self
+                .alloc
+                .alloc_order(0)
+                .expect("out of memory servicing a demand-paging fault");
+            frame.refcount().store(1, Ordering::Release);
+            let new_pfn = frame.into();
+
+            unsafe {
+                Access::get_ptr_for_pfn(new_pfn)
+                    .as_ptr()
+                    .cast::<u8>()
+                    .write_bytes(0, PAGE_SIZE);
+            }
+
+            pte.set(
+                new_pfn,
+                <<Mode as PagingMode>::Entry as PTE>::Attr::from_page_attr(area.attr),
+            );
+            return true;
+        }
+
+        if is_write && page_attr.contains(PageAttribute::COPY_ON_WRITE) {
+            let frame = self
+                .alloc
+                .alloc_order(0)
+                .expect("out of memory servicing a COW fault");
+            frame.refcount().store(1, Ordering::Release);
+            let new_pfn = frame.into();
+
+            unsafe {
+                let src = Access::get_ptr_for_pfn(pfn).as_ptr().cast::<u8>();
+                let dst = Access::get_ptr_for_pfn(new_pfn).as_ptr().cast::<u8>();
+                core::ptr::copy_nonoverlapping(src, dst, PAGE_SIZE);
+            }
+
+            // Only the last mapping sharing the old frame actually frees it.
+            let old_frame = Alloc::RawPage::from(pfn);
+            if old_frame.refcount().fetch_sub(1, Ordering::AcqRel) == 1 {
+                unsafe { self.alloc.dealloc(old_frame) };
+            }
+
+            let new_attr = (area.attr | PageAttribute::WRITE) - PageAttribute::COPY_ON_WRITE;
+            pte.set(
+                new_pfn,
+                <<Mode as PagingMode>::Entry as PTE>::Attr::from_page_attr(new_attr),
+            );
+            return true;
+        }
+
+        false
+    }
+}
@@ -196,3 +196,164 @@ impl PagingMode for RV39 {
 
     const KERNEL_ROOT_TABLE_PFN: PFN = PFN::from_val(0x80000000);
 }
+
+/// Sv48: adds a fourth table level on top of Sv39, widening the virtual
+/// address space to 48 bits. The PTE format is unchanged from Sv39.
+#[cfg(feature = "riscv.pagetable.sv48")]
+pub struct RV48;
+
+/// Sign-extends `vaddr` from bit 47 so that Sv48 addresses stay canonical
+/// (bits 48..=63 must copy bit 47).
+#[cfg(feature = "riscv.pagetable.sv48")]
+pub const fn canonicalize_sv48(vaddr: usize) -> usize {
+    ((vaddr as isize) << 16 >> 16) as usize
+}
+
+#[cfg(feature = "riscv.pagetable.sv48")]
+impl PagingMode for RV48 {
+    type Entry = RV39PTE;
+
+    type RawTable<'a> = RV39RawTable<'a>;
+
+    const LEVELS: &'static [PageTableLevel] = &[
+        PageTableLevel::new(39, 9),
+        PageTableLevel::new(30, 9),
+        PageTableLevel::new(21, 9),
+        PageTableLevel::new(12, 9),
+    ];
+
+    const KERNEL_ROOT_TABLE_PFN: PFN = PFN::from_val(0x80000000);
+}
+
+/// Sv57: adds a fifth table level on top of Sv48, widening the virtual
+/// address space to 57 bits. The PTE format is unchanged from Sv39.
+#[cfg(feature = "riscv.pagetable.sv57")]
+pub struct RV57;
+
+/// Sign-extends `vaddr` from bit 56 so that Sv57 addresses stay canonical
+/// (bits 57..=63 must copy bit 56).
+#[cfg(feature = "riscv.pagetable.sv57")]
+pub const fn canonicalize_sv57(vaddr: usize) -> usize {
+    ((vaddr as isize) << 7 >> 7) as usize
+}
+
+#[cfg(feature = "riscv.pagetable.sv57")]
+impl PagingMode for RV57 {
+    type Entry = RV39PTE;
+
+    type RawTable<'a> = RV39RawTable<'a>;
+
+    const LEVELS: &'static [PageTableLevel] = &[
+        PageTableLevel::new(48, 9),
+        PageTableLevel::new(39, 9),
+        PageTableLevel::new(30, 9),
+        PageTableLevel::new(21, 9),
+        PageTableLevel::new(12, 9),
+    ];
+
+    const KERNEL_ROOT_TABLE_PFN: PFN = PFN::from_val(0x80000000);
+}
+
+/// Sv32: the 32-bit paging mode. PTEs are 32 bits wide with a 22-bit PPN,
+/// giving a 34-bit physical address space, and the table is two levels of
+/// 1024 entries (10-bit VPN indices), with 4 MiB megapages at the top
+/// level. The flag layout matches [`RV39PTE`], just packed into a `u32`.
+#[cfg(feature = "riscv.riscv32")]
+pub struct RV32;
+#[cfg(feature = "riscv.riscv32")]
+pub struct RV32PTE(u32);
+#[cfg(feature = "riscv.riscv32")]
+pub struct RV32RawTable<'a>(NonNull<RV32PTE>, PhantomData<&'a ()>);
+
+#[cfg(feature = "riscv.riscv32")]
+impl PTE for RV32PTE {
+    type Attr = Attribute;
+
+    fn set(&mut self, pfn: PFN, attr: Self::Attr) {
+        self.0 = (usize::from(pfn) << 10) as u32 | attr.0 as u32;
+    }
+
+    fn get(&self) -> (PFN, Self::Attr) {
+        let pfn = PFN::from(self.0 as usize >> 10);
+        let attr = Attribute((self.0 & 0x3FF) as u64);
+        (pfn, attr)
+    }
+}
+
+#[cfg(feature = "riscv.riscv32")]
+impl<'a> RawPageTable<'a> for RV32RawTable<'a> {
+    type Entry = RV32PTE;
+
+    fn index(&self, index: u16) -> &'a Self::Entry {
+        unsafe { self.0.add(index as usize).as_ref() }
+    }
+
+    fn index_mut(&mut self, index: u16) -> &'a mut Self::Entry {
+        unsafe { self.0.add(index as usize).as_mut() }
+    }
+
+    unsafe fn from_ptr(ptr: NonNull<PageBlock>) -> Self {
+        Self(ptr.cast(), PhantomData)
+    }
+}
+
+#[cfg(feature = "riscv.riscv32")]
+impl PagingMode for RV32 {
+    type Entry = RV32PTE;
+
+    type RawTable<'a> = RV32RawTable<'a>;
+
+    const LEVELS: &'static [PageTableLevel] = &[
+        PageTableLevel::new(22, 10),
+        PageTableLevel::new(12, 10),
+    ];
+
+    const KERNEL_ROOT_TABLE_PFN: PFN = PFN::from_val(0x80000000);
+}
+
+/// Canonicalizes a kernel virtual address for whichever [`PagingModeImpl`]
+/// is active: a no-op on Sv39/Sv32, [`canonicalize_sv48`] under Sv48, and
+/// [`canonicalize_sv57`] under Sv57, following the same feature precedence
+/// as [`PagingModeImpl`] itself.
+#[cfg(all(not(feature = "riscv.riscv32"), feature = "riscv.pagetable.sv57"))]
+pub const fn canonicalize_addr(vaddr: usize) -> usize {
+    canonicalize_sv57(vaddr)
+}
+#[cfg(all(
+    not(feature = "riscv.riscv32"),
+    feature = "riscv.pagetable.sv48",
+    not(feature = "riscv.pagetable.sv57")
+))]
+pub const fn canonicalize_addr(vaddr: usize) -> usize {
+    canonicalize_sv48(vaddr)
+}
+#[cfg(any(
+    feature = "riscv.riscv32",
+    all(
+        not(feature = "riscv.pagetable.sv48"),
+        not(feature = "riscv.pagetable.sv57")
+    )
+))]
+pub const fn canonicalize_addr(vaddr: usize) -> usize {
+    vaddr
+}
+
+/// Resolves to whichever [`PagingMode`] impl the enabled `riscv.*` features
+/// select, so `PageTable` picks up the right mode per target without the
+/// rest of the kernel having to know which mode is active.
+#[cfg(feature = "riscv.riscv32")]
+pub type PagingModeImpl = RV32;
+#[cfg(all(not(feature = "riscv.riscv32"), feature = "riscv.pagetable.sv57"))]
+pub type PagingModeImpl = RV57;
+#[cfg(all(
+    not(feature = "riscv.riscv32"),
+    feature = "riscv.pagetable.sv48",
+    not(feature = "riscv.pagetable.sv57")
+))]
+pub type PagingModeImpl = RV48;
+#[cfg(all(
+    not(feature = "riscv.riscv32"),
+    not(feature = "riscv.pagetable.sv48"),
+    not(feature = "riscv.pagetable.sv57")
+))]
+pub type PagingModeImpl = RV39;